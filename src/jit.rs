@@ -0,0 +1,342 @@
+//! Just-in-time compilation of a bf instruction stream into native x86-64 machine code.
+//!
+//! [`jit_run`] is an alternative to [`crate::run`]: instead of dispatching each
+//! [`Instructions`] one at a time in the interpreter's `while i < inst.len()` loop, the
+//! whole program is translated once into a buffer of machine code that is mapped
+//! executable and jumped into directly. This trades a one-time compilation pass for the
+//! elimination of per-instruction dispatch overhead, which pays off on long-running
+//! programs.
+//!
+//! Only x86-64 is supported; the code generated here assumes the SysV calling
+//! convention (Linux/macOS).
+
+use std::io::{self, BufReader, Read, Stdin};
+use std::os::raw::c_void;
+use std::sync::{Mutex, OnceLock};
+
+use crate::Instructions::{self, *};
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+}
+
+// Trampolines called from generated code so I/O can stay plain Rust instead of inlined
+// syscalls. Called with the cell value in `dil`/returned in `al`, per the SysV ABI.
+extern "C" fn jit_putchar(byte: u8) {
+    print!("{}", char::from(byte));
+}
+
+// Buffered so repeated `,` reads don't trip `clippy::unbuffered_bytes`, and kept alive
+// for the process's lifetime so bytes read ahead into the buffer aren't discarded
+// between calls.
+fn stdin_reader() -> &'static Mutex<BufReader<Stdin>> {
+    static STDIN: OnceLock<Mutex<BufReader<Stdin>>> = OnceLock::new();
+    STDIN.get_or_init(|| Mutex::new(BufReader::new(io::stdin())))
+}
+
+extern "C" fn jit_getchar() -> u8 {
+    let mut byte = [0u8; 1];
+    match stdin_reader().lock().unwrap().read(&mut byte) {
+        Ok(0) => panic!("Could not read from stdin"),
+        Ok(_) => byte[0],
+        Err(_) => 0,
+    }
+}
+
+// `AddMultiple`'s target index wraps the same way `IncrementPointer`/`DecrementPointer`
+// do (see `crate::step_idx`), which is fiddly to inline in asm, so it gets a trampoline
+// like PrintChar/ReadChar instead.
+extern "C" fn jit_add_multiple(base: *mut u8, len: u64, idx: u64, offset: i64, factor: u8) {
+    let target = crate::step_idx(idx as usize, offset as isize, len as usize);
+    unsafe {
+        let origin = *base.add(idx as usize);
+        let cell = base.add(target);
+        *cell = (*cell).wrapping_add(origin.wrapping_mul(factor));
+    }
+}
+
+/// Accumulates machine code bytes and back-patches rel32 jump fields once their target
+/// is known. The bracket matching itself is done by `jit_run`'s `loop_stack`, mirroring
+/// the `stack`/`jump` bookkeeping in [`crate::run_bounded`].
+struct Assembler {
+    code: Vec<u8>,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler { code: Vec::new() }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, v: u32) {
+        self.emit(&v.to_le_bytes());
+    }
+
+    fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    // Prologue: save the caller's rbx (callee-saved) and load idx into it. Operands:
+    // rdi = base pointer, rsi = memory length, rdx = initial idx.
+    fn prologue(&mut self) {
+        self.emit(&[0x53]); // push rbx
+        self.emit(&[0x48, 0x89, 0xD3]); // mov rbx, rdx
+    }
+
+    // Epilogue: return idx in rax and restore the caller's rbx.
+    fn epilogue(&mut self) {
+        self.emit(&[0x48, 0x89, 0xD8]); // mov rax, rbx
+        self.emit(&[0x5B]); // pop rbx
+        self.emit(&[0xC3]); // ret
+    }
+
+    // add byte [rdi+rbx], imm8
+    fn add_byte_ptr(&mut self, imm: u8) {
+        self.emit(&[0x80, 0x04, 0x1F, imm]);
+    }
+
+    // sub byte [rdi+rbx], imm8
+    fn sub_byte_ptr(&mut self, imm: u8) {
+        self.emit(&[0x80, 0x2C, 0x1F, imm]);
+    }
+
+    // mov byte [rdi+rbx], 0
+    fn zero_byte_ptr(&mut self) {
+        self.emit(&[0xC6, 0x04, 0x1F, 0x00]);
+    }
+
+    // cmp byte [rdi+rbx], 0
+    fn cmp_byte_ptr_zero(&mut self) {
+        self.emit(&[0x80, 0x3C, 0x1F, 0x00]);
+    }
+
+    // idx += x; idx %= len (rsi), same as the unconditional `idx %= memory.len()` in run()
+    fn incr_idx(&mut self, x: u32) {
+        self.emit(&[0x48, 0x81, 0xC3]); // add rbx, imm32
+        self.emit_u32(x);
+        self.emit(&[0x48, 0x89, 0xD8]); // mov rax, rbx
+        self.emit(&[0x48, 0x31, 0xD2]); // xor rdx, rdx
+        self.emit(&[0x48, 0xF7, 0xF6]); // div rsi
+        self.emit(&[0x48, 0x89, 0xD3]); // mov rbx, rdx
+    }
+
+    // Mirrors the `if x > idx { idx = len - (x - idx) } else { idx -= x }` branch in run().
+    fn decr_idx(&mut self, x: u32) {
+        self.emit(&[0x48, 0x81, 0xFB]); // cmp rbx, imm32
+        self.emit_u32(x);
+        let jb_at = self.jb_placeholder(); // jump to the wrap-around path if idx < x
+
+        self.emit(&[0x48, 0x81, 0xEB]); // sub rbx, imm32
+        self.emit_u32(x);
+        let done_at = self.jmp_placeholder();
+
+        self.patch_rel32(jb_at, self.len());
+        self.emit(&[0x48, 0xC7, 0xC0]); // mov rax, imm32 (x)
+        self.emit_u32(x);
+        self.emit(&[0x48, 0x29, 0xD8]); // sub rax, rbx  (rax = x - idx)
+        self.emit(&[0x48, 0x89, 0xF3]); // mov rbx, rsi  (rbx = len)
+        self.emit(&[0x48, 0x29, 0xC3]); // sub rbx, rax  (rbx = len - (x - idx))
+
+        self.patch_rel32(done_at, self.len());
+    }
+
+    fn jz_placeholder(&mut self) -> usize {
+        self.emit(&[0x0F, 0x84]);
+        let at = self.len();
+        self.emit_u32(0);
+        at
+    }
+
+    fn jnz_to(&mut self, target: usize) {
+        self.emit(&[0x0F, 0x85]);
+        let at = self.len();
+        self.emit_u32(0);
+        self.patch_rel32(at, target);
+    }
+
+    fn jb_placeholder(&mut self) -> usize {
+        self.emit(&[0x0F, 0x82]);
+        let at = self.len();
+        self.emit_u32(0);
+        at
+    }
+
+    fn jmp_placeholder(&mut self) -> usize {
+        self.emit(&[0xE9]);
+        let at = self.len();
+        self.emit_u32(0);
+        at
+    }
+
+    fn patch_rel32(&mut self, at: usize, target: usize) {
+        let rel = target as i64 - (at as i64 + 4);
+        self.code[at..at + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+    }
+
+    // Calls out to a Rust trampoline. Saves nothing itself: callers wrap it in
+    // save_call_regs/restore_call_regs to keep rdi (base) and rsi (len); idx lives in
+    // rbx, which the callee preserves.
+    fn call_trampoline(&mut self, addr: usize) {
+        self.emit(&[0x48, 0xB8]); // mov rax, imm64
+        self.emit(&(addr as u64).to_le_bytes());
+        self.emit(&[0x48, 0xFF, 0xD0]); // call rax
+    }
+
+    fn print_char(&mut self) {
+        self.emit(&[0x0F, 0xB6, 0x04, 0x1F]); // movzx eax, byte [rdi+rbx]
+        self.save_call_regs();
+        self.emit(&[0x48, 0x89, 0xC7]); // mov rdi, rax
+        self.call_trampoline(jit_putchar as *const () as usize);
+        self.restore_call_regs();
+    }
+
+    fn read_char(&mut self) {
+        self.save_call_regs();
+        self.call_trampoline(jit_getchar as *const () as usize);
+        self.restore_call_regs();
+        self.emit(&[0x88, 0x04, 0x1F]); // mov byte [rdi+rbx], al
+    }
+
+    fn add_multiple(&mut self, offset: isize, factor: u8) {
+        self.save_call_regs();
+        self.emit(&[0x48, 0x89, 0xDA]); // mov rdx, rbx        (idx)
+        self.emit(&[0x48, 0xC7, 0xC1]); // mov rcx, imm32      (offset, sign-extended)
+        self.emit_u32(offset as i32 as u32);
+        self.emit(&[0x41, 0xB8]); // mov r8d, imm32            (factor)
+        self.emit_u32(factor as u32);
+        self.call_trampoline(jit_add_multiple as *const () as usize);
+        self.restore_call_regs();
+    }
+
+    // while memory[idx] != 0 { idx = step_idx(idx, step, len) }, reusing the same
+    // modulo arithmetic as incr_idx/decr_idx
+    fn seek_zero(&mut self, step: isize) {
+        let loop_start = self.len();
+        self.cmp_byte_ptr_zero();
+        let jz_at = self.jz_placeholder();
+
+        if step >= 0 {
+            self.incr_idx(step as u32);
+        } else {
+            self.decr_idx((-step) as u32);
+        }
+
+        let jmp_at = self.jmp_placeholder();
+        self.patch_rel32(jmp_at, loop_start);
+
+        self.patch_rel32(jz_at, self.len());
+    }
+
+    // rdi (base) and rsi (len) are caller-saved per the SysV ABI, but we keep them live
+    // across the whole function, so any call out to a trampoline has to save and
+    // restore them around the call.
+    fn save_call_regs(&mut self) {
+        self.emit(&[0x57]); // push rdi
+        self.emit(&[0x56]); // push rsi
+    }
+
+    fn restore_call_regs(&mut self) {
+        self.emit(&[0x5E]); // pop rsi
+        self.emit(&[0x5F]); // pop rdi
+    }
+}
+
+/// An executable buffer holding the compiled program. Unmapped on drop.
+struct CodeBuffer {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl CodeBuffer {
+    fn new(code: &[u8]) -> Self {
+        let len = code.len();
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        // mmap signals failure with MAP_FAILED ((void *)-1), not null
+        assert!(ptr as isize != -1, "mmap failed to allocate executable memory");
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(code.as_ptr(), ptr as *mut u8, len);
+            assert_eq!(mprotect(ptr, len, PROT_READ | PROT_EXEC), 0, "mprotect failed");
+        }
+
+        CodeBuffer { ptr, len }
+    }
+
+    unsafe fn call(&self, base: *mut u8, len: u64, idx: u64) -> u64 {
+        let f: extern "C" fn(*mut u8, u64, u64) -> u64 = std::mem::transmute(self.ptr);
+        f(base, len, idx)
+    }
+}
+
+impl Drop for CodeBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// JIT-compiles `inst` down to native machine code and runs it directly against `memory`,
+/// instead of interpreting it instruction-by-instruction as [`crate::run`] does. Returns
+/// the index the pointer ends up at.
+pub fn jit_run(inst: &[Instructions], memory: &mut [u8]) -> usize {
+    let mut asm = Assembler::new();
+    let mut loop_stack: Vec<usize> = Vec::new();
+
+    asm.prologue();
+
+    for instruction in inst {
+        match instruction {
+            IncrementPointer(x) => asm.incr_idx(*x as u32),
+            DecrementPointer(x) => asm.decr_idx(*x as u32),
+            IncrementValue(x) => asm.add_byte_ptr(*x as u8),
+            DecrementValue(x) => asm.sub_byte_ptr(*x as u8),
+            SetZero => asm.zero_byte_ptr(),
+            AddMultiple { offset, factor } => asm.add_multiple(*offset, *factor),
+            SeekZero { step } => asm.seek_zero(*step),
+            BeginLoop => {
+                asm.cmp_byte_ptr_zero();
+                let jz_at = asm.jz_placeholder();
+                loop_stack.push(jz_at);
+                // The matching EndLoop jumps back to this BeginLoop's jz. Its own cmp has
+                // just found the cell nonzero, so the jz falls through into the body.
+                loop_stack.push(asm.len() - 6); // offset of this BeginLoop's jz
+            }
+            EndLoop => {
+                let loop_start = loop_stack.pop().expect("Could not find matching '['");
+                let jz_at = loop_stack.pop().expect("Could not find matching '['");
+                asm.cmp_byte_ptr_zero();
+                asm.jnz_to(loop_start);
+                asm.patch_rel32(jz_at, asm.len());
+            }
+            ReadChar => asm.read_char(),
+            PrintChar => asm.print_char(),
+        }
+    }
+
+    asm.epilogue();
+
+    let code = CodeBuffer::new(&asm.code);
+    let final_idx = unsafe { code.call(memory.as_mut_ptr(), memory.len() as u64, 0) };
+    final_idx as usize
+}
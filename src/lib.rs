@@ -1,6 +1,15 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{self, BufWriter, Read, Write};
 
+mod jit;
+mod program;
+mod tape;
+
+pub use jit::jit_run;
+pub use program::{CompileError, Program};
+pub use tape::{Cell, Tape, DEFAULT_TAPE_LEN};
+
 use Instructions::*;
 
 /// The tuple enum variants hold a value that represents how many times the instruction should be repeated. This overcomes the overhead of repeating the same task over and over in the form of 'unit operations'
@@ -26,6 +35,12 @@ pub enum Instructions {
     ///
     /// Equivalent to [-] and [+] (set current cell to 0), but in one instruction
     SetZero,
+    /// Equivalent to a balanced copy/multiply loop such as [->+<] or [->++>+++<<]: add
+    /// `factor` times the current cell's value to the cell at `offset` relative to it
+    AddMultiple { offset: isize, factor: u8 },
+    /// Equivalent to a loop like [>] or [<] that runs the pointer to the next/previous
+    /// zero cell: advance the pointer by `step` repeatedly until it lands on a zero cell
+    SeekZero { step: isize },
 }
 
 /// Translates the code from a string of chars to a Vec of Instructions to be later matched against properly in run(). Returns a vector with the instructions in the order that they appear, but with some optimizations
@@ -105,6 +120,8 @@ pub fn parse(program: &str, optimize: bool, verbose: bool) -> Vec<Instructions>
             }
         }
 
+        let optimized = optimize_special_loops(optimized);
+
         if verbose {
             println!(
                 "Optimized set of instructions contains {} operators",
@@ -117,14 +134,126 @@ pub fn parse(program: &str, optimize: bool, verbose: bool) -> Vec<Instructions>
     }
 }
 
+/// Finds loops with well-known special-cased bodies and collapses each into a handful
+/// of O(1) instructions, replacing what would otherwise be an O(value) loop:
+/// - balanced copy/multiply loops (e.g. `[->+<]`, `[->++>+++<<]`) become `AddMultiple`
+///   instructions followed by a `SetZero`
+/// - scan loops (`[>]`, `[<]`) become a single `SeekZero`
+///
+/// Loops that don't match either pattern are left untouched.
+fn optimize_special_loops(instructions: Vec<Instructions>) -> Vec<Instructions> {
+    let mut optimized = Vec::with_capacity(instructions.len());
+    let mut i = 0;
+
+    while i < instructions.len() {
+        if instructions[i] == BeginLoop {
+            if let Some((body, end)) = multiply_loop_body(&instructions, i) {
+                optimized.extend(body);
+                i = end + 1;
+                continue;
+            }
+            if let Some((body, end)) = seek_zero_body(&instructions, i) {
+                optimized.extend(body);
+                i = end + 1;
+                continue;
+            }
+        }
+        optimized.push(instructions[i]);
+        i += 1;
+    }
+
+    optimized
+}
+
+/// If the loop starting at `instructions[start]` (a `BeginLoop`) has a body of exactly
+/// one `IncrementPointer`/`DecrementPointer`, returns the `SeekZero` it should be
+/// replaced with and the index of its matching `EndLoop`.
+fn seek_zero_body(instructions: &[Instructions], start: usize) -> Option<(Vec<Instructions>, usize)> {
+    let step = match instructions.get(start + 1)? {
+        IncrementPointer(x) => *x as isize,
+        DecrementPointer(x) => -(*x as isize),
+        _ => return None,
+    };
+    if *instructions.get(start + 2)? != EndLoop {
+        return None;
+    }
+
+    Some((vec![SeekZero { step }], start + 2))
+}
+
+/// If the loop starting at `instructions[start]` (a `BeginLoop`) only moves the pointer
+/// and adds/subtracts values (no I/O, no nesting), leaves the pointer back where it
+/// started, and decrements the origin cell by exactly one per iteration, returns the
+/// instructions it should be replaced with and the index of its matching `EndLoop`.
+fn multiply_loop_body(instructions: &[Instructions], start: usize) -> Option<(Vec<Instructions>, usize)> {
+    let mut ptr_offset: isize = 0;
+    let mut deltas: BTreeMap<isize, isize> = BTreeMap::new();
+    let mut end = start + 1;
+
+    while end < instructions.len() {
+        match instructions[end] {
+            IncrementPointer(x) => ptr_offset += x as isize,
+            DecrementPointer(x) => ptr_offset -= x as isize,
+            IncrementValue(x) => *deltas.entry(ptr_offset).or_insert(0) += x as isize,
+            DecrementValue(x) => *deltas.entry(ptr_offset).or_insert(0) -= x as isize,
+            EndLoop => break,
+            // I/O, nesting, or an already-folded instruction: not a pattern we collapse
+            _ => return None,
+        }
+        end += 1;
+    }
+
+    if end == instructions.len() {
+        return None; // unmatched '[': not a loop body, leave it untouched
+    }
+    if ptr_offset != 0 {
+        return None; // pointer must end up back where the loop started
+    }
+    if deltas.remove(&0) != Some(-1) {
+        return None; // origin cell must be decremented by exactly one per iteration
+    }
+    // AddMultiple only adds, so a cell the loop would subtract from disqualifies it
+    if deltas.values().any(|&delta| delta <= 0 || delta > u8::MAX as isize) {
+        return None;
+    }
+
+    let mut body: Vec<Instructions> = deltas
+        .into_iter()
+        .map(|(offset, delta)| AddMultiple {
+            offset,
+            factor: delta as u8,
+        })
+        .collect();
+    body.push(SetZero);
+
+    Some((body, end))
+}
+
+/// Outcome of a bounded run: whether the program ran to completion, or was stopped
+/// early after exceeding its step budget
+#[derive(Debug, PartialEq)]
+pub enum Halt {
+    /// The program reached the end of its instructions within the step budget
+    Completed { actions: usize },
+    /// Execution was stopped after performing `actions` instructions without finishing.
+    /// `idx` is the index of the instruction that would have run next, so a caller can
+    /// report (or resume) a suspected non-halting program instead of hanging
+    StepLimitReached { idx: usize, actions: usize },
+}
+
 /// Here's where the magic happens. With the course of action extracted with the parse() function, the only thing that is left to do is to take the appropriate action given an instruction
 /// Returns the number of executed instructions and the index the pointer points at
-pub fn run(inst: &[Instructions], memory: &mut [u8], mut idx: usize) -> (usize, usize) {
-    // Variable to keep track of how many instructions were performed
-    let mut actions: usize = 0;
-    // Index of current instruction
-    let mut i = 0;
+pub fn run<C: Cell>(inst: &[Instructions], memory: &mut [C], idx: usize) -> (usize, usize) {
+    match run_bounded(inst, memory, idx, usize::MAX) {
+        (Halt::Completed { actions }, idx) => (actions, idx),
+        (Halt::StepLimitReached { .. }, _) => unreachable!("usize::MAX steps should never be reached"),
+    }
+}
 
+/// Same as [`run`], but halts and returns [`Halt::StepLimitReached`] once `actions`
+/// would exceed `max_steps`, instead of running forever on a non-terminating program.
+/// This makes it safe to execute untrusted bf input, e.g. in a REPL or a fuzzer
+pub fn run_bounded<C: Cell>(inst: &[Instructions], memory: &mut [C], idx: usize, max_steps: usize) -> (Halt, usize) {
     // Indexes of begin loops to keep track of nested loops. Only used to fill jump
     let mut stack: Vec<usize> = Vec::new();
     // Vec with indexes of jumps to be made during execution (loops)
@@ -143,8 +272,29 @@ pub fn run(inst: &[Instructions], memory: &mut [u8], mut idx: usize) -> (usize,
         }
     }
 
+    execute(inst, &jump, memory, idx, max_steps)
+}
+
+/// The hot execution loop shared by [`run_bounded`] (which scans `inst` for its jump
+/// table on every call) and [`Program::run_bounded`] (which reuses a precomputed one)
+pub(crate) fn execute<C: Cell>(
+    inst: &[Instructions],
+    jump: &[usize],
+    memory: &mut [C],
+    mut idx: usize,
+    max_steps: usize,
+) -> (Halt, usize) {
+    // Variable to keep track of how many instructions were performed
+    let mut actions: usize = 0;
+    // Index of current instruction
+    let mut i = 0;
+
     // Loop through all intructions
     while i < inst.len() {
+        if actions >= max_steps {
+            return (Halt::StepLimitReached { idx: i, actions }, idx);
+        }
+
         match inst[i] {
             // If idx is equal to the last position, return to the first
             IncrementPointer(x) => {
@@ -160,18 +310,18 @@ pub fn run(inst: &[Instructions], memory: &mut [u8], mut idx: usize) -> (usize,
                 }
             }
             IncrementValue(x) => {
-                memory[idx] = memory[idx].wrapping_add(x as u8);
+                memory[idx] = memory[idx].wrapping_add(x);
             }
             DecrementValue(x) => {
-                memory[idx] = memory[idx].wrapping_sub(x as u8);
+                memory[idx] = memory[idx].wrapping_sub(x);
             }
             BeginLoop => {
-                if memory[idx] == 0 {
+                if memory[idx].is_zero() {
                     i = jump[i];
                 }
             }
             EndLoop => {
-                if memory[idx] != 0 {
+                if !memory[idx].is_zero() {
                     i = jump[i];
                 }
             }
@@ -181,25 +331,61 @@ pub fn run(inst: &[Instructions], memory: &mut [u8], mut idx: usize) -> (usize,
                     .next()
                     .expect("Could not read from stdin")
                 {
-                    memory[idx] = ch
+                    memory[idx] = C::from_byte(ch)
+                }
+            }
+            PrintChar => print!("{}", char::from(memory[idx].to_byte())),
+            SetZero => memory[idx] = C::default(),
+            AddMultiple { offset, factor } => {
+                // Wraps like IncrementPointer/DecrementPointer instead of dropping the
+                // write, so folding a loop into AddMultiple can't change a program's
+                // observable output at the tape edges.
+                let target = step_idx(idx, offset, memory.len());
+                let value = memory[idx];
+                memory[target] = memory[target].wrapping_add_cell(value.wrapping_mul(factor));
+            }
+            SeekZero { step } => {
+                while !memory[idx].is_zero() {
+                    // Leave room for the SeekZero instruction's own action, counted below
+                    if actions + 1 >= max_steps {
+                        return (Halt::StepLimitReached { idx: i, actions }, idx);
+                    }
+                    idx = step_idx(idx, step, memory.len());
+                    actions += 1;
                 }
             }
-            PrintChar => print!("{}", char::from(memory[idx])),
-            SetZero => memory[idx] = 0,
         }
         actions += 1;
         i += 1;
     }
-    (actions, idx)
+    (Halt::Completed { actions }, idx)
+}
+
+/// Advances `idx` by `step` (positive or negative), wrapping the same way
+/// `IncrementPointer`/`DecrementPointer` do. Also used by the JIT's `AddMultiple`
+/// trampoline, so a target that wraps past the tape edge behaves identically either way.
+pub(crate) fn step_idx(idx: usize, step: isize, len: usize) -> usize {
+    if step >= 0 {
+        (idx + step as usize) % len
+    } else {
+        let step = (-step) as usize;
+        if step > idx {
+            len - (step - idx)
+        } else {
+            idx - step
+        }
+    }
 }
 
 /// Dump memory to file
-pub fn dump_mem(memory: &[u8], file: File, addr: usize) -> io::Result<()> {
+pub fn dump_mem<C: Cell>(memory: &[C], file: File, addr: usize) -> io::Result<()> {
     // Buffer the output
     let mut buf = BufWriter::new(file);
 
     // Quantity of bytes (different memory positions) per line
     let step = 12;
+    // Hex digits needed to represent a single cell at its configured width
+    let hex_width = C::BYTES * 2;
 
     buf.write_all(format!("Pointer pointing at address 0x{:04X}\n\n", addr).as_bytes())?;
 
@@ -207,7 +393,7 @@ pub fn dump_mem(memory: &[u8], file: File, addr: usize) -> io::Result<()> {
         buf.write_all(format!("0x{:04X}: \t", i).as_bytes())?;
 
         for value in memory.iter().skip(i).take(step) {
-            buf.write_all(format!("0x{:02X} \t", value).as_bytes())?;
+            buf.write_all(format!("0x{:0width$X} \t", value.to_u64(), width = hex_width).as_bytes())?;
         }
 
         // Format last line properly if it is shorter than the previous ones
@@ -218,8 +404,9 @@ pub fn dump_mem(memory: &[u8], file: File, addr: usize) -> io::Result<()> {
         }
 
         for value in memory.iter().skip(i).take(step) {
-            if value.is_ascii_graphic() {
-                buf.write_all(format!("{}", *value as char).as_bytes())?;
+            let byte = value.to_byte();
+            if byte.is_ascii_graphic() {
+                buf.write_all(format!("{}", byte as char).as_bytes())?;
             } else {
                 buf.write_all(b".")?;
             }
@@ -239,3 +426,57 @@ pub fn dump_inst(instructions: &[Instructions], mut file: File) -> io::Result<()
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Regression test for a SeekZero whose target never hits zero: it must respect
+    // max_steps instead of spinning forever (the whole point of run_bounded).
+    #[test]
+    fn seek_zero_respects_step_budget() {
+        let inst = parse("+>+>+>+[>]", true, false);
+        let mut memory = [1u8; 4];
+
+        let (halt, _idx) = run_bounded(&inst, &mut memory, 0, 1000);
+
+        match halt {
+            Halt::StepLimitReached { actions, .. } => assert!(actions <= 1000),
+            Halt::Completed { .. } => panic!("scan over an all-nonzero tape should never complete"),
+        }
+    }
+
+    // Regression test for AddMultiple: folding a copy loop must not change a program's
+    // observable output relative to running it unoptimized, even when the loop's offset
+    // would carry the target past the tape's edge.
+    #[test]
+    fn add_multiple_agrees_with_unoptimized_at_tape_edges() {
+        let program = "+++[-<+>]";
+
+        let unoptimized = parse(program, false, false);
+        let mut expected = [0u8; 4];
+        run(&unoptimized, &mut expected, 0);
+
+        let optimized = parse(program, true, false);
+        let mut actual = [0u8; 4];
+        run(&optimized, &mut actual, 0);
+
+        assert_eq!(actual, expected);
+    }
+
+    // The JIT is a second implementation of the same semantics; it must agree with the
+    // interpreter on a program that exercises both AddMultiple and SeekZero.
+    #[test]
+    fn jit_agrees_with_interpreter() {
+        let program = "+++++[->++>+++<<]>[-<+>]>.<<[>]";
+        let inst = parse(program, true, false);
+
+        let mut interpreted = [0u8; 8];
+        run(&inst, &mut interpreted, 0);
+
+        let mut jitted = [0u8; 8];
+        jit_run(&inst, &mut jitted);
+
+        assert_eq!(interpreted, jitted);
+    }
+}
@@ -0,0 +1,85 @@
+//! [`Program`] separates the "build the bracket-matching jump table" phase from the hot
+//! execution loop. [`run`](crate::run)/[`run_bounded`](crate::run_bounded) rebuild that
+//! table from scratch on every call, which is wasted work when the same program is
+//! executed repeatedly (a REPL, fuzzing, benchmarking); compiling it once up front
+//! avoids the repeated scan.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::execute;
+use crate::Instructions::{self, *};
+use crate::{Cell, Halt};
+
+/// A bf instruction stream's brackets don't match up
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    /// A `]` with no preceding unmatched `[`, at this instruction index
+    UnmatchedEndLoop { idx: usize },
+    /// A `[` that was never closed, at this instruction index
+    UnmatchedBeginLoop { idx: usize },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UnmatchedEndLoop { idx } => write!(f, "unmatched ']' at instruction {}", idx),
+            CompileError::UnmatchedBeginLoop { idx } => write!(f, "unmatched '[' at instruction {}", idx),
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+/// An instruction stream with its jump table precomputed and validated once by
+/// [`Program::compile`], so [`Program::run`]/[`Program::run_bounded`] can go straight
+/// to execution.
+pub struct Program {
+    instructions: Vec<Instructions>,
+    jump: Vec<usize>,
+}
+
+impl Program {
+    /// Validates `inst`'s brackets and precomputes its jump table, returning an error
+    /// for an unmatched `[` or `]`. The free-standing `run`/`run_bounded` instead panic
+    /// on an unmatched `]` and do not detect an unmatched `[`
+    pub fn compile(inst: &[Instructions]) -> Result<Self, CompileError> {
+        let mut stack: Vec<usize> = Vec::new();
+        let mut jump: Vec<usize> = vec![0; inst.len()];
+
+        for (i, instruction) in inst.iter().enumerate() {
+            match instruction {
+                BeginLoop => stack.push(i),
+                EndLoop => {
+                    let index = stack.pop().ok_or(CompileError::UnmatchedEndLoop { idx: i })?;
+                    jump[i] = index;
+                    jump[index] = i;
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(&idx) = stack.first() {
+            return Err(CompileError::UnmatchedBeginLoop { idx });
+        }
+
+        Ok(Program {
+            instructions: inst.to_vec(),
+            jump,
+        })
+    }
+
+    /// Runs the compiled program against `memory`, starting at `idx`. See [`crate::run`]
+    pub fn run<C: Cell>(&self, memory: &mut [C], idx: usize) -> (usize, usize) {
+        match self.run_bounded(memory, idx, usize::MAX) {
+            (Halt::Completed { actions }, idx) => (actions, idx),
+            (Halt::StepLimitReached { .. }, _) => unreachable!("usize::MAX steps should never be reached"),
+        }
+    }
+
+    /// Runs the compiled program against `memory`, starting at `idx`, halting early per
+    /// [`crate::run_bounded`] instead of rescanning the program for its jump table first
+    pub fn run_bounded<C: Cell>(&self, memory: &mut [C], idx: usize, max_steps: usize) -> (Halt, usize) {
+        execute(&self.instructions, &self.jump, memory, idx, max_steps)
+    }
+}
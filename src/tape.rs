@@ -0,0 +1,149 @@
+//! The interpreter's memory was originally hardcoded to `&mut [u8]`. [`Cell`] abstracts
+//! over the cell width so a program can opt into 16- or 32-bit cells, and [`Tape`] is a
+//! tape of cells of a chosen width and length, with [`DEFAULT_TAPE_LEN`] matching the
+//! interpreter's original fixed size of 256.
+
+/// Default number of cells a [`Tape`] is given when no size is requested explicitly
+pub const DEFAULT_TAPE_LEN: usize = 256;
+
+/// A memory cell of some fixed width. `IncrementValue`/`DecrementValue` add or subtract
+/// a run-length-encoded count (`parse` groups consecutive `+`/`-`), which is applied at
+/// the cell's own width rather than truncated through a `u8` so e.g. 300 `+` in a row on
+/// a 16-bit cell really does add 300. `AddMultiple`'s `factor` stays `u8` (it comes from
+/// counting `+`/`-` inside a loop body, which realistically never exceeds 255), but the
+/// product it scales by the origin cell's value is added at full cell width via
+/// `wrapping_add_cell`.
+pub trait Cell: Copy + Default + PartialEq + 'static {
+    /// Width of a single cell in bytes, used by `dump_mem` to format cells correctly
+    const BYTES: usize;
+
+    fn wrapping_add(self, x: usize) -> Self;
+    fn wrapping_sub(self, x: usize) -> Self;
+    fn wrapping_mul(self, x: u8) -> Self;
+    /// Adds another cell's value, wrapping at this cell's width
+    fn wrapping_add_cell(self, other: Self) -> Self;
+    fn is_zero(self) -> bool;
+
+    /// Truncates to the low byte, used for stdin/stdout which only ever deal in bytes
+    fn to_byte(self) -> u8;
+    fn from_byte(byte: u8) -> Self;
+    /// Widens to u64 so `dump_mem` can format any cell width with one code path
+    fn to_u64(self) -> u64;
+}
+
+impl Cell for u8 {
+    const BYTES: usize = 1;
+
+    fn wrapping_add(self, x: usize) -> Self {
+        u8::wrapping_add(self, x as u8)
+    }
+    fn wrapping_sub(self, x: usize) -> Self {
+        u8::wrapping_sub(self, x as u8)
+    }
+    fn wrapping_mul(self, x: u8) -> Self {
+        u8::wrapping_mul(self, x)
+    }
+    fn wrapping_add_cell(self, other: Self) -> Self {
+        u8::wrapping_add(self, other)
+    }
+    fn is_zero(self) -> bool {
+        self == 0
+    }
+    fn to_byte(self) -> u8 {
+        self
+    }
+    fn from_byte(byte: u8) -> Self {
+        byte
+    }
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+}
+
+impl Cell for u16 {
+    const BYTES: usize = 2;
+
+    fn wrapping_add(self, x: usize) -> Self {
+        u16::wrapping_add(self, x as u16)
+    }
+    fn wrapping_sub(self, x: usize) -> Self {
+        u16::wrapping_sub(self, x as u16)
+    }
+    fn wrapping_mul(self, x: u8) -> Self {
+        u16::wrapping_mul(self, x as u16)
+    }
+    fn wrapping_add_cell(self, other: Self) -> Self {
+        u16::wrapping_add(self, other)
+    }
+    fn is_zero(self) -> bool {
+        self == 0
+    }
+    fn to_byte(self) -> u8 {
+        self as u8
+    }
+    fn from_byte(byte: u8) -> Self {
+        byte as u16
+    }
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+}
+
+impl Cell for u32 {
+    const BYTES: usize = 4;
+
+    fn wrapping_add(self, x: usize) -> Self {
+        u32::wrapping_add(self, x as u32)
+    }
+    fn wrapping_sub(self, x: usize) -> Self {
+        u32::wrapping_sub(self, x as u32)
+    }
+    fn wrapping_mul(self, x: u8) -> Self {
+        u32::wrapping_mul(self, x as u32)
+    }
+    fn wrapping_add_cell(self, other: Self) -> Self {
+        u32::wrapping_add(self, other)
+    }
+    fn is_zero(self) -> bool {
+        self == 0
+    }
+    fn to_byte(self) -> u8 {
+        self as u8
+    }
+    fn from_byte(byte: u8) -> Self {
+        byte as u32
+    }
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+}
+
+/// A program's memory: `len` cells of width `C`, all starting at zero
+pub struct Tape<C: Cell> {
+    cells: Vec<C>,
+}
+
+impl<C: Cell> Tape<C> {
+    /// Allocates a tape of `len` cells, all initialized to zero
+    pub fn new(len: usize) -> Self {
+        Tape {
+            cells: vec![C::default(); len],
+        }
+    }
+
+    pub fn as_slice(&self) -> &[C] {
+        &self.cells
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [C] {
+        &mut self.cells
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}